@@ -12,6 +12,41 @@ use util::{path_to_cstring, as_path};
 use explain::{Explainable, exists, user};
 
 
+/// The propagation type to apply to a mount after it's been bound.
+///
+/// These map directly to the kernel's `MS_SHARED`/`MS_PRIVATE`/`MS_SLAVE`/
+/// `MS_UNBINDABLE` flags and are combined with `MS_REC` when the
+/// `BindMount` is recursive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    Shared,
+    Private,
+    Slave,
+    Unbindable,
+}
+
+impl Propagation {
+    fn ms_flag(&self) -> flags::MsFlags {
+        match *self {
+            Propagation::Shared => flags::MS_SHARED,
+            Propagation::Private => flags::MS_PRIVATE,
+            Propagation::Slave => flags::MS_SLAVE,
+            Propagation::Unbindable => flags::MS_UNBINDABLE,
+        }
+    }
+}
+
+impl fmt::Display for Propagation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match *self {
+            Propagation::Shared => "shared",
+            Propagation::Private => "private",
+            Propagation::Slave => "slave",
+            Propagation::Unbindable => "unbindable",
+        })
+    }
+}
+
 /// A mount bind definition
 ///
 /// By default bind mount is recursive (it's what you want most of the time).
@@ -22,6 +57,8 @@ pub struct BindMount {
     source: CString,
     target: CString,
     recursive: bool,
+    propagation: Option<Propagation>,
+    remount_ro: bool,
 }
 
 impl BindMount {
@@ -35,6 +72,8 @@ impl BindMount {
             source: path_to_cstring(source.as_ref()),
             target: path_to_cstring(target.as_ref()),
             recursive: true,
+            propagation: None,
+            remount_ro: false,
         }
     }
     /// Toggle recursion
@@ -43,23 +82,63 @@ impl BindMount {
         self
     }
 
+    /// Mark the bound subtree shared/private/slave/unbindable
+    ///
+    /// This issues a second `mount(2)` call after the bind, since the
+    /// kernel ignores propagation flags passed to the initial bind.
+    pub fn propagation(mut self, propagation: Propagation) -> BindMount {
+        self.propagation = Some(propagation);
+        self
+    }
+
+    /// Remount the bound subtree read-only
+    ///
+    /// The kernel also ignores most per-mount flags (like `MS_RDONLY`) on
+    /// the initial bind, so this issues a follow-up
+    /// `MS_BIND | MS_REMOUNT | MS_RDONLY` remount.
+    pub fn remount_ro(mut self) -> BindMount {
+        self.remount_ro = true;
+        self
+    }
+
     /// Execute a bind mount
     pub fn bare_mount(self) -> Result<(), OSError> {
-        let mut flags = flags::MS_BIND;
+        let mut bind_flags = flags::MS_BIND;
         if self.recursive {
-            flags = flags | flags::MS_REC;
+            bind_flags = bind_flags | flags::MS_REC;
         }
         let rc = unsafe { mount(
                 self.source.as_ptr(),
                 self.target.as_ptr(),
                 null(),
-                flags.bits(),
+                bind_flags.bits(),
                 null()) };
         if rc < 0 {
-            Err(OSError(io::Error::last_os_error(), Box::new(self)))
-        } else {
-            Ok(())
+            return Err(OSError(io::Error::last_os_error(), Box::new(self)));
+        }
+
+        if let Some(propagation) = self.propagation {
+            let mut prop_flags = propagation.ms_flag();
+            if self.recursive {
+                prop_flags = prop_flags | flags::MS_REC;
+            }
+            let rc = unsafe { mount(
+                    null(), self.target.as_ptr(), null(), prop_flags.bits(), null()) };
+            if rc < 0 {
+                return Err(OSError(io::Error::last_os_error(), Box::new(self)));
+            }
         }
+
+        if self.remount_ro {
+            let remount_flags = flags::MS_BIND | flags::MS_REMOUNT | flags::MS_RDONLY;
+            let rc = unsafe { mount(
+                    null(), self.target.as_ptr(), null(), remount_flags.bits(), null()) };
+            if rc < 0 {
+                return Err(OSError(io::Error::last_os_error(), Box::new(self)));
+            }
+        }
+
+        Ok(())
     }
 
     /// Execute a bind mount and explain the error immediately
@@ -73,18 +152,32 @@ impl fmt::Display for BindMount {
         if self.recursive {
             try!(write!(fmt, "recursive "));
         }
-        write!(fmt, "bind mount {:?} -> {:?}",
-            as_path(&self.source), as_path(&self.target))
+        try!(write!(fmt, "bind mount {:?} -> {:?}",
+            as_path(&self.source), as_path(&self.target)));
+        if let Some(propagation) = self.propagation {
+            try!(write!(fmt, " ({})", propagation));
+        }
+        if self.remount_ro {
+            try!(write!(fmt, ", remount read-only"));
+        }
+        Ok(())
     }
 }
 
 impl Explainable for BindMount {
     fn explain(&self) -> String {
-        [
+        let mut parts = vec![
             format!("source: {}", exists(as_path(&self.source))),
             format!("target: {}", exists(as_path(&self.target))),
             format!("{}", user()),
-        ].join(", ")
+        ];
+        if let Some(propagation) = self.propagation {
+            parts.push(format!("propagation: {}", propagation));
+        }
+        if self.remount_ro {
+            parts.push(format!("remount: read-only"));
+        }
+        parts.join(", ")
     }
 }
 
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::error::Error as StdError;
+use std::path::Path;
+
+use libc::c_ulong;
+
+use mountinfo::{MountsParser, MountInfo, MountsParserError};
+
+/// Failure to build a `MountTable`.
+#[derive(Debug)]
+pub enum MountTableError {
+    Open(io::Error),
+    Parse(MountsParserError),
+}
+
+impl fmt::Display for MountTableError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MountTableError::Open(ref e) => write!(fmt, "Error opening mountinfo: {}", e),
+            MountTableError::Parse(ref e) => write!(fmt, "Error parsing mountinfo: {}", e),
+        }
+    }
+}
+
+impl StdError for MountTableError {
+    fn description(&self) -> &str {
+        match *self {
+            MountTableError::Open(_) => "error opening mountinfo",
+            MountTableError::Parse(_) => "error parsing mountinfo",
+        }
+    }
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            MountTableError::Open(ref e) => Some(e),
+            MountTableError::Parse(ref e) => Some(e),
+        }
+    }
+}
+
+/// A snapshot of the currently mounted filesystems.
+///
+/// Built by reading all rows out of `/proc/self/mountinfo` (or any other
+/// reader in the same format) up front, then queried by path or by the
+/// `parent_id` links between rows.
+pub struct MountTable {
+    mounts: Vec<MountInfo>,
+}
+
+impl MountTable {
+    /// Read and parse the current process's `/proc/self/mountinfo`
+    pub fn read_self() -> Result<MountTable, MountTableError> {
+        let file = try!(File::open("/proc/self/mountinfo").map_err(MountTableError::Open));
+        MountTable::from_reader(file)
+    }
+
+    /// Build a table from an arbitrary reader in `mountinfo` format
+    pub fn from_reader<R: Read>(reader: R) -> Result<MountTable, MountTableError> {
+        let mut mounts = Vec::new();
+        for row in MountsParser::new(reader) {
+            mounts.push(try!(row.map_err(MountTableError::Parse)));
+        }
+        Ok(MountTable { mounts: mounts })
+    }
+
+    /// All mounts, in the order they appeared in `mountinfo`
+    pub fn mounts(&self) -> &[MountInfo] {
+        &self.mounts
+    }
+
+    /// The mount whose `mount_point` is the longest path-component prefix
+    /// of `path`, i.e. the filesystem that backs `path`.
+    pub fn find_mount<P: AsRef<Path>>(&self, path: P) -> Option<&MountInfo> {
+        let path = path.as_ref();
+        self.mounts.iter()
+            .filter(|m| is_component_prefix(&m.mount_point, path))
+            .max_by_key(|m| m.mount_point.components().count())
+    }
+
+    /// The mount whose `mount_point` is exactly `path`
+    pub fn by_mount_point<P: AsRef<Path>>(&self, path: P) -> Option<&MountInfo> {
+        let path = path.as_ref();
+        self.mounts.iter().find(|m| m.mount_point == path)
+    }
+
+    /// The mounts directly below `mount_id` in the mount tree
+    pub fn children(&self, mount_id: c_ulong) -> Vec<&MountInfo> {
+        self.mounts.iter().filter(|m| m.parent_id == mount_id).collect()
+    }
+}
+
+/// Whether every path component of `prefix` matches the leading
+/// components of `path` (so `/foo` is a prefix of `/foo/bar` but not of
+/// `/foobar`).
+fn is_component_prefix(prefix: &Path, path: &Path) -> bool {
+    let mut prefix_components = prefix.components();
+    let mut path_components = path.components();
+    loop {
+        match prefix_components.next() {
+            None => return true,
+            Some(prefix_component) => {
+                match path_components.next() {
+                    None => return false,
+                    Some(path_component) => {
+                        if prefix_component != path_component {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use super::MountTable;
+
+    fn table(content: &str) -> MountTable {
+        MountTable::from_reader(Cursor::new(content.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_find_mount_longest_prefix() {
+        let t = table("\
+            19 1 0:1 / / rw - ext4 /dev/sda1 rw\n\
+            20 19 0:2 / /home rw shared:1 - ext4 /dev/sda2 rw\n\
+            21 20 0:3 / /home/user rw shared:2 - ext4 /dev/sda3 rw\n");
+        let m = t.find_mount("/home/user/docs/file.txt").unwrap();
+        assert_eq!(m.mount_point, Path::new("/home/user"));
+
+        let m = t.find_mount("/home/other").unwrap();
+        assert_eq!(m.mount_point, Path::new("/home"));
+
+        let m = t.find_mount("/etc/passwd").unwrap();
+        assert_eq!(m.mount_point, Path::new("/"));
+    }
+
+    #[test]
+    fn test_find_mount_does_not_match_sibling_with_shared_prefix() {
+        let t = table("\
+            19 1 0:1 / / rw - ext4 /dev/sda1 rw\n\
+            20 19 0:2 / /foo rw - ext4 /dev/sda2 rw\n");
+        let m = t.find_mount("/foobar/baz").unwrap();
+        assert_eq!(m.mount_point, Path::new("/"));
+    }
+
+    #[test]
+    fn test_by_mount_point_exact_match() {
+        let t = table("\
+            19 1 0:1 / / rw - ext4 /dev/sda1 rw\n\
+            20 19 0:2 / /proc rw - proc proc rw\n");
+        assert!(t.by_mount_point("/proc").is_some());
+        assert!(t.by_mount_point("/pro").is_none());
+    }
+
+    #[test]
+    fn test_children_walk_mount_tree() {
+        let t = table("\
+            19 1 0:1 / / rw - ext4 /dev/sda1 rw\n\
+            20 19 0:2 / /proc rw - proc proc rw\n\
+            21 19 0:3 / /sys rw - sysfs sysfs rw\n\
+            22 20 0:4 / /proc/sys rw - proc proc rw\n");
+        let mut children: Vec<_> = t.children(19).iter().map(|m| m.mount_id).collect();
+        children.sort();
+        assert_eq!(children, vec![20, 21]);
+        assert_eq!(t.children(20).len(), 1);
+        assert_eq!(t.children(999).len(), 0);
+    }
+}
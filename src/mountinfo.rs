@@ -3,27 +3,84 @@ use std::io::{BufReader, BufRead, Read, Split, Error as IOError};
 use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::PathBuf;
 use std::borrow::Cow;
+use std::error::Error as StdError;
+use std::fmt;
 
 use libc::c_ulong;
 use libc::{MS_RDONLY, MS_NOSUID, MS_NODEV, MS_NOEXEC, MS_SYNCHRONOUS};
 use libc::{MS_MANDLOCK, MS_DIRSYNC, MS_NOATIME, MS_NODIRATIME};
 use libc::{MS_RELATIME, MS_STRICTATIME};
+use nix::mount as flags;
+
+/// The line number and raw (lossy-UTF8) text of the row a parse error
+/// occurred on, so callers can point a user at the offending line of
+/// `/proc/self/mountinfo`.
+#[derive(Debug)]
+pub struct RowContext {
+    pub line: usize,
+    pub row: String,
+}
+
+impl RowContext {
+    fn new(line: usize, row: &[u8]) -> RowContext {
+        RowContext {
+            line: line,
+            row: String::from_utf8_lossy(row).into_owned(),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum MountsParserError {
-    Read(String, IOError),
-    IncompleteRow(String),
-    InvalidValue(String),
+    Read(IOError),
+    IncompleteRow(RowContext),
+    InvalidValue(RowContext, String),
+}
+
+impl fmt::Display for MountsParserError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MountsParserError::Read(ref e) => {
+                write!(fmt, "Error reading mounts file: {}", e)
+            }
+            MountsParserError::IncompleteRow(ref ctx) => {
+                write!(fmt, "Parse error at line {}: expected more values\n{}",
+                    ctx.line, ctx.row)
+            }
+            MountsParserError::InvalidValue(ref ctx, ref message) => {
+                write!(fmt, "Parse error at line {}: {}\n{}",
+                    ctx.line, message, ctx.row)
+            }
+        }
+    }
+}
+
+impl StdError for MountsParserError {
+    fn description(&self) -> &str {
+        match *self {
+            MountsParserError::Read(_) => "error reading mounts file",
+            MountsParserError::IncompleteRow(_) => "incomplete row in mounts file",
+            MountsParserError::InvalidValue(_, _) => "invalid value in mounts file",
+        }
+    }
+    fn cause(&self) -> Option<&StdError> {
+        match *self {
+            MountsParserError::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 pub struct MountsParser<R: Read> {
-    rows: Split<BufReader<R>>
+    rows: Split<BufReader<R>>,
+    line: usize,
 }
 
 impl<R: Read> MountsParser<R> {
     pub fn new(mounts_file: R) -> MountsParser<R> {
         MountsParser {
             rows: BufReader::new(mounts_file).split(b'\n'),
+            line: 0,
         }
     }
 }
@@ -42,7 +99,75 @@ pub struct MountInfo {
     pub super_options: OsString,
 }
 
+/// A single propagation tag as found in the `optional_fields` column of
+/// `/proc/self/mountinfo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropagationTag {
+    /// `shared:N` -- this mount is a member of peer group N.
+    Shared(OsString),
+    /// `master:N` -- this mount is a slave of peer group N.
+    Slave(OsString),
+    /// `propagate_from:N` -- mount events also propagate from peer group N.
+    PropagateFrom(OsString),
+    /// `unbindable` -- this mount cannot be bind-mounted.
+    Unbindable,
+    /// A tag this parser doesn't recognize yet, kept verbatim so future
+    /// kernels don't silently lose information.
+    Unknown(OsString),
+}
+
+/// The propagation state of a mount, decoded from `optional_fields`.
+///
+/// A mount can carry more than one tag at once (e.g. be `shared` *and*
+/// `propagate_from` another peer group), so this collects every tag
+/// found rather than picking just one.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Propagation {
+    pub tags: Vec<PropagationTag>,
+}
+
+impl Propagation {
+    /// No optional fields were present: a private, non-shared mount.
+    pub fn is_private(&self) -> bool {
+        self.tags.is_empty()
+    }
+    pub fn is_shared(&self) -> bool {
+        self.tags.iter().any(|t| match *t {
+            PropagationTag::Shared(_) => true,
+            _ => false,
+        })
+    }
+    pub fn is_unbindable(&self) -> bool {
+        self.tags.iter().any(|t| match *t {
+            PropagationTag::Unbindable => true,
+            _ => false,
+        })
+    }
+}
+
 impl MountInfo {
+    /// Parse the `optional_fields` column into structured propagation tags.
+    pub fn propagation(&self) -> Propagation {
+        let mut tags = Vec::new();
+        for field in self.optional_fields.as_bytes().split(|c| *c == b' ') {
+            if field.is_empty() {
+                continue;
+            }
+            let field = OsStr::from_bytes(field);
+            let mut parts = field.as_bytes().splitn(2, |c| *c == b':');
+            let tag = parts.next().unwrap_or(b"");
+            let value = parts.next().map(|v| OsStr::from_bytes(v).to_os_string());
+            tags.push(match (tag, value) {
+                (b"shared", Some(v)) => PropagationTag::Shared(v),
+                (b"master", Some(v)) => PropagationTag::Slave(v),
+                (b"propagate_from", Some(v)) => PropagationTag::PropagateFrom(v),
+                (b"unbindable", None) => PropagationTag::Unbindable,
+                _ => PropagationTag::Unknown(field.to_os_string()),
+            });
+        }
+        Propagation { tags: tags }
+    }
+
     pub fn get_flags(&self) -> c_ulong {
         let mut flags = 0 as c_ulong;
         for opt in self.mount_options.as_bytes().split(|c| *c == b',') {
@@ -61,6 +186,55 @@ impl MountInfo {
         }
         flags
     }
+
+    /// Like `get_flags`, but returns a `nix::mount::MsFlags` bitset
+    /// together with every `mount_options`/`super_options` token that
+    /// isn't a recognized kernel flag (e.g. `data=ordered`, `mode=755`),
+    /// so callers can tell "flag not set" apart from "option this parser
+    /// doesn't map to a flag". `rw` is dropped: it only means `MS_RDONLY`
+    /// is absent and is not returned among the options.
+    pub fn flags_and_options(&self) -> (flags::MsFlags, Vec<(OsString, Option<OsString>)>) {
+        let mut bits = flags::MsFlags::empty();
+        let mut options = Vec::new();
+        collect_options(&self.mount_options, &mut bits, &mut options);
+        collect_options(&self.super_options, &mut bits, &mut options);
+        (bits, options)
+    }
+}
+
+fn collect_options(raw: &OsStr, bits: &mut flags::MsFlags, options: &mut Vec<(OsString, Option<OsString>)>) {
+    for opt in raw.as_bytes().split(|c| *c == b',') {
+        if opt.is_empty() {
+            continue;
+        }
+        let opt = OsStr::from_bytes(opt);
+        let known_flag = match opt.to_str() {
+            Some("ro") => Some(flags::MS_RDONLY),
+            Some("nosuid") => Some(flags::MS_NOSUID),
+            Some("nodev") => Some(flags::MS_NODEV),
+            Some("noexec") => Some(flags::MS_NOEXEC),
+            Some("mand") => Some(flags::MS_MANDLOCK),
+            Some("sync") => Some(flags::MS_SYNCHRONOUS),
+            Some("dirsync") => Some(flags::MS_DIRSYNC),
+            Some("noatime") => Some(flags::MS_NOATIME),
+            Some("nodiratime") => Some(flags::MS_NODIRATIME),
+            Some("relatime") => Some(flags::MS_RELATIME),
+            Some("strictatime") => Some(flags::MS_STRICTATIME),
+            _ => None,
+        };
+        if let Some(bit) = known_flag {
+            *bits = *bits | bit;
+            continue;
+        }
+        if opt == OsStr::new("rw") {
+            // Absence of MS_RDONLY, not a settable flag.
+            continue;
+        }
+        let mut parts = opt.as_bytes().splitn(2, |c| *c == b'=');
+        let key = OsStr::from_bytes(parts.next().unwrap_or(b"")).to_os_string();
+        let value = parts.next().map(|v| OsStr::from_bytes(v).to_os_string());
+        options.push((key, value));
+    }
 }
 
 macro_rules! itry {
@@ -80,32 +254,33 @@ impl<R: Read> Iterator for MountsParser<R> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.rows.next() {
             Some(Ok(mut row)) => {
+                self.line += 1;
+                let line = self.line;
+
                 if row.ends_with(&[b'\r']) {
                     let new_len = row.len() - 1;
                     row.truncate(new_len);
                 }
 
                 let invalid_format = || {
-                    MountsParserError::IncompleteRow(format!("Expected more values"))
+                    MountsParserError::IncompleteRow(RowContext::new(line, &row))
                 };
 
                 // Whitespaces are escaped in /proc/mounts
                 let mut columns = row.split(|c| *c == b' ');
-                let mount_id = itry!(parse_int(&mut columns, &row));
-                let parent_id = itry!(parse_int(&mut columns, &row));
+                let mount_id = itry!(parse_int(&mut columns, &row, line));
+                let parent_id = itry!(parse_int(&mut columns, &row, line));
                 let mut major_minor = itry!(columns.next().ok_or_else(&invalid_format))
                     .split(|c| *c == b':');
-                let major = itry!(parse_int(&mut major_minor, &row));
-                let minor = itry!(parse_int(&mut major_minor, &row));
-                let root = itry!(parse_path(&mut columns, &row));
-                let mount_point = itry!(parse_path(&mut columns, &row));
-                let mount_options = itry!(parse_os_str(&mut columns, &row));
-                let optional_fields = itry!(parse_os_str(&mut columns, &row));
-                let separator = itry!(columns.next().ok_or_else(&invalid_format));
-                assert_eq!(separator, b"-");
-                let fstype = itry!(parse_os_str(&mut columns, &row));
-                let mount_source = itry!(parse_os_str(&mut columns, &row));
-                let super_options = itry!(parse_os_str(&mut columns, &row));
+                let major = itry!(parse_int(&mut major_minor, &row, line));
+                let minor = itry!(parse_int(&mut major_minor, &row, line));
+                let root = itry!(parse_path(&mut columns, &row, line));
+                let mount_point = itry!(parse_path(&mut columns, &row, line));
+                let mount_options = itry!(parse_os_str(&mut columns, &row, line));
+                let optional_fields = itry!(parse_optional_fields(&mut columns, &row, line));
+                let fstype = itry!(parse_os_str(&mut columns, &row, line));
+                let mount_source = itry!(parse_os_str(&mut columns, &row, line));
+                let super_options = itry!(parse_os_str(&mut columns, &row, line));
 
                 Some(Ok(MountInfo {
                     mount_id: mount_id,
@@ -122,57 +297,75 @@ impl<R: Read> Iterator for MountsParser<R> {
                 }))
             },
             Some(Err(e)) => {
-                Some(Err(MountsParserError::Read(format!("Error when reading mounts file"), e)))
+                self.line += 1;
+                Some(Err(MountsParserError::Read(e)))
             },
             None => None,
         }
     }
 }
 
-fn parse_os_str(columns: &mut Iterator<Item=&[u8]>, row: &[u8])
+fn parse_os_str(columns: &mut Iterator<Item=&[u8]>, row: &[u8], line: usize)
     -> Result<OsString, MountsParserError>
 {
     let bytes = try!(columns.next()
-        .ok_or_else(|| MountsParserError::IncompleteRow(
-            format!("Expected more values in row: {:?}",
-                String::from_utf8_lossy(row)))));
+        .ok_or_else(|| MountsParserError::IncompleteRow(RowContext::new(line, row))));
     let mut value = Cow::Borrowed(bytes);
-    try!(unescape_octals(&mut value));
+    try!(unescape_octals(&mut value, row, line));
     Ok(OsString::from_vec(value.into_owned()))
 }
 
-fn parse_int(columns: &mut Iterator<Item=&[u8]>, row: &[u8])
+fn parse_int(columns: &mut Iterator<Item=&[u8]>, row: &[u8], line: usize)
     -> Result<c_ulong, MountsParserError>
 {
     let col = try!(columns.next()
-        .ok_or_else(|| MountsParserError::IncompleteRow(
-            format!("Expected more values for row: {:?}",
-                String::from_utf8_lossy(row))))
+        .ok_or_else(|| MountsParserError::IncompleteRow(RowContext::new(line, row)))
         .map(|v| String::from_utf8_lossy(v)));
     col.parse::<c_ulong>()
         .map_err(|_| MountsParserError::InvalidValue(
-            format!("Cannot parse integer from {:?}: {:?}",
-                col, String::from_utf8_lossy(row))))
+            RowContext::new(line, row),
+            format!("Cannot parse integer from {:?}", col)))
 }
 
-fn parse_path(columns: &mut Iterator<Item=&[u8]>, row: &[u8])
+fn parse_path(columns: &mut Iterator<Item=&[u8]>, row: &[u8], line: usize)
     -> Result<PathBuf, MountsParserError>
 {
-    Ok(PathBuf::from(try!(parse_os_str(columns, row))))
+    Ok(PathBuf::from(try!(parse_os_str(columns, row, line))))
+}
+
+/// Consume the zero-or-more `optional_fields` tokens up to (and
+/// including) the `-` separator, joining them back with single spaces.
+fn parse_optional_fields(columns: &mut Iterator<Item=&[u8]>, row: &[u8], line: usize)
+    -> Result<OsString, MountsParserError>
+{
+    let mut joined = Vec::new();
+    loop {
+        let token = try!(columns.next()
+            .ok_or_else(|| MountsParserError::IncompleteRow(RowContext::new(line, row))));
+        if token == b"-" {
+            break;
+        }
+        if !joined.is_empty() {
+            joined.push(b' ');
+        }
+        joined.extend_from_slice(token);
+    }
+    Ok(OsString::from_vec(joined))
 }
 
-fn unescape_octals(v: &mut Cow<[u8]>) -> Result<(), MountsParserError>{
+fn unescape_octals(v: &mut Cow<[u8]>, row: &[u8], line: usize) -> Result<(), MountsParserError>{
     let mut i = 0;
     loop {
         if v[i] == b'\\' {
             let tail = v.to_mut().split_off(i);
             if tail.len() < 4 {
-                return Err(MountsParserError::InvalidValue(format!("Invalid escaping")));
+                return Err(MountsParserError::InvalidValue(
+                    RowContext::new(line, row), format!("Invalid escaping")));
             }
             let oct = String::from_utf8_lossy(&tail[1..4]);
             let b = try!(u8::from_str_radix(&oct, 8)
                 .map_err(|_| MountsParserError::InvalidValue(
-                    format!("Expected octal number"))));
+                    RowContext::new(line, row), format!("Expected octal number"))));
             v.to_mut().push(b);
             v.to_mut().extend_from_slice(&tail[4..]);
         }
@@ -193,7 +386,9 @@ mod test {
 
     use libc::{MS_NOSUID, MS_NODEV, MS_NOEXEC, MS_RELATIME};
 
-    use super::{MountsParser, MountsParserError};
+    use nix::mount as msflags;
+
+    use super::{MountsParser, MountsParserError, PropagationTag};
 
     #[test]
     fn test_mount_info_parser_proc() {
@@ -270,6 +465,57 @@ mod test {
         assert!(parser.next().is_none());
     }
 
+    #[test]
+    fn test_propagation_shared() {
+        let content = "19 24 0:4 / /proc rw shared:12 - proc proc rw";
+        let reader = Cursor::new(content.as_bytes());
+        let mut parser = MountsParser::new(reader);
+        let mount_info = parser.next().unwrap().unwrap();
+        let propagation = mount_info.propagation();
+        assert_eq!(propagation.tags, vec![PropagationTag::Shared(OsStr::new("12").to_os_string())]);
+        assert!(propagation.is_shared());
+        assert!(!propagation.is_private());
+        assert!(!propagation.is_unbindable());
+    }
+
+    #[test]
+    fn test_propagation_shared_and_propagate_from() {
+        let content = "19 24 0:4 / /proc rw shared:12 propagate_from:7 - proc proc rw";
+        let reader = Cursor::new(content.as_bytes());
+        let mut parser = MountsParser::new(reader);
+        let mount_info = parser.next().unwrap().unwrap();
+        let propagation = mount_info.propagation();
+        assert_eq!(propagation.tags, vec![
+            PropagationTag::Shared(OsStr::new("12").to_os_string()),
+            PropagationTag::PropagateFrom(OsStr::new("7").to_os_string()),
+        ]);
+        assert!(propagation.is_shared());
+    }
+
+    #[test]
+    fn test_propagation_unbindable_and_unknown_tag() {
+        let content = "19 24 0:4 / /proc rw unbindable future:1 - proc proc rw";
+        let reader = Cursor::new(content.as_bytes());
+        let mut parser = MountsParser::new(reader);
+        let mount_info = parser.next().unwrap().unwrap();
+        let propagation = mount_info.propagation();
+        assert_eq!(propagation.tags, vec![
+            PropagationTag::Unbindable,
+            PropagationTag::Unknown(OsStr::new("future:1").to_os_string()),
+        ]);
+        assert!(propagation.is_unbindable());
+    }
+
+    #[test]
+    fn test_propagation_private() {
+        let content = "19 24 0:4 / /proc rw - proc proc rw";
+        let reader = Cursor::new(content.as_bytes());
+        let mut parser = MountsParser::new(reader);
+        let mount_info = parser.next().unwrap().unwrap();
+        let propagation = mount_info.propagation();
+        assert!(propagation.is_private());
+    }
+
     #[test]
     fn test_mount_info_parser_incomplete_row() {
         let content = "19 24 0:4 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc";
@@ -278,7 +524,9 @@ mod test {
         let mount_info_res = parser.next().unwrap();
         assert!(mount_info_res.is_err());
         match mount_info_res {
-            Err(MountsParserError::IncompleteRow(_)) => {}
+            Err(MountsParserError::IncompleteRow(ref ctx)) => {
+                assert_eq!(ctx.line, 1);
+            }
             _ => panic!("Expected incomplete row error")
         }
         assert!(parser.next().is_none());
@@ -292,7 +540,10 @@ mod test {
         let mount_info_res = parser.next().unwrap();
         assert!(mount_info_res.is_err());
         match mount_info_res {
-            Err(MountsParserError::InvalidValue(_)) => {}
+            Err(MountsParserError::InvalidValue(ref ctx, ref message)) => {
+                assert_eq!(ctx.line, 1);
+                assert!(message.contains("24b"));
+            }
             _ => panic!("Expected invalid row error")
         }
         assert!(parser.next().is_none());
@@ -306,9 +557,59 @@ mod test {
         let mount_info_res = parser.next().unwrap();
         assert!(mount_info_res.is_err());
         match mount_info_res {
-            Err(MountsParserError::InvalidValue(_)) => {}
+            Err(MountsParserError::InvalidValue(_, _)) => {}
             _ => panic!("Expected invalid row error")
         }
         assert!(parser.next().is_none());
     }
+
+    #[test]
+    fn test_mount_info_parser_error_reports_line_number() {
+        let content = "19 24 0:4 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw\n\
+                       19 24b 0:4 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw\n";
+        let reader = Cursor::new(content.as_bytes());
+        let mut parser = MountsParser::new(reader);
+        assert!(parser.next().unwrap().is_ok());
+        let err = parser.next().unwrap().unwrap_err();
+        let rendered = format!("{}", err);
+        assert!(rendered.starts_with("Parse error at line 2:"), "{}", rendered);
+        assert!(rendered.contains("24b"));
+    }
+
+    #[test]
+    fn test_flags_and_options_ext4() {
+        let content = r"76 24 8:6 / /home/my\040super\046name rw,relatime shared:29 - ext4 /dev/sda1 rw,data=ordered";
+        let reader = Cursor::new(content.as_bytes());
+        let mut parser = MountsParser::new(reader);
+        let mount_info = parser.next().unwrap().unwrap();
+        let (flags, options) = mount_info.flags_and_options();
+        assert_eq!(flags, msflags::MS_RELATIME);
+        assert_eq!(options, vec![
+            (OsStr::new("data").to_os_string(), Some(OsStr::new("ordered").to_os_string())),
+        ]);
+    }
+
+    #[test]
+    fn test_flags_and_options_tmpfs_mode() {
+        let content = b"22 24 0:19 / /tmp rw,nosuid shared:5 - tmpfs tmpfs rw,mode=755";
+        let reader = Cursor::new(&content[..]);
+        let mut parser = MountsParser::new(reader);
+        let mount_info = parser.next().unwrap().unwrap();
+        let (flags, options) = mount_info.flags_and_options();
+        assert_eq!(flags, msflags::MS_NOSUID);
+        assert_eq!(options, vec![
+            (OsStr::new("mode").to_os_string(), Some(OsStr::new("755").to_os_string())),
+        ]);
+    }
+
+    #[test]
+    fn test_flags_and_options_merges_super_options_flags() {
+        let content = "19 24 0:4 / /proc rw,nosuid - proc proc rw,noexec";
+        let reader = Cursor::new(content.as_bytes());
+        let mut parser = MountsParser::new(reader);
+        let mount_info = parser.next().unwrap().unwrap();
+        let (flags, options) = mount_info.flags_and_options();
+        assert_eq!(flags, msflags::MS_NOSUID | msflags::MS_NOEXEC);
+        assert!(options.is_empty());
+    }
 }